@@ -1,4 +1,9 @@
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
 use pyo3::{exceptions::PyValueError, prelude::*};
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
 use serde::{Deserialize, Serialize};
 
 use anyhow::{anyhow, Result};
@@ -15,6 +20,15 @@ pub fn geoid_string_to_link_id(origin: &GeoidString, destination: &GeoidString)
     format!("{}-{}", origin, destination)
 }
 
+/// Decodes a geoid's H3 cell center to a `(lon, lat)` point.
+pub fn geoid_to_lonlat(geoid: &GeoidString) -> Result<(f64, f64)> {
+    let cell: h3o::CellIndex = geoid
+        .parse()
+        .map_err(|e| anyhow!("Invalid H3 cell geoid {}: {}", geoid, e))?;
+    let center = h3o::LatLng::from(cell);
+    Ok((center.lng(), center.lat()))
+}
+
 pub fn link_id_to_geoids(link_id: &LinkId) -> Result<(GeoidString, GeoidString)> {
     let ids: Vec<&str> = link_id.split("-").collect();
     if ids.len() != 2 {
@@ -31,18 +45,26 @@ pub fn link_id_to_geoids(link_id: &LinkId) -> Result<(GeoidString, GeoidString)>
 pub struct HaversineRoadNetwork {
     #[pyo3(get)]
     sim_h3_resolution: usize,
+    // Flattened across every polygon/hole. Empty means unrestricted.
+    geofence_rings: Vec<Vec<(f64, f64)>>,
 }
 
 #[pymethods]
 impl HaversineRoadNetwork {
     #[new]
-    fn new(sim_h3_resolution: Option<usize>) -> PyResult<Self> {
+    fn new(sim_h3_resolution: Option<usize>, geofence_geojson: Option<String>) -> PyResult<Self> {
         let res = match sim_h3_resolution {
             Some(res) => res,
             None => 15,
         };
+        let geofence_rings = match geofence_geojson {
+            Some(geojson) => parse_geofence_rings(&geojson)
+                .map_err(|e| PyValueError::new_err(format!("Invalid geofence GeoJSON: {}", e)))?,
+            None => Vec::new(),
+        };
         Ok(HaversineRoadNetwork {
             sim_h3_resolution: res,
+            geofence_rings,
         })
     }
 
@@ -125,7 +147,1330 @@ impl HaversineRoadNetwork {
         }
     }
 
-    fn geoid_within_geofence(&self, _geoid: GeoidString) -> bool {
-        true
+    fn geoid_within_geofence(&self, geoid: GeoidString) -> PyResult<bool> {
+        if self.geofence_rings.is_empty() {
+            return Ok(true);
+        }
+        let point = geoid_to_lonlat(&geoid)
+            .map_err(|e| PyValueError::new_err(format!("Failure decoding geoid: {}", e)))?;
+        Ok(point_in_rings(point, &self.geofence_rings))
+    }
+
+    fn filter_geoids(&self, geoids: Vec<GeoidString>) -> PyResult<Vec<GeoidString>> {
+        geoids
+            .into_iter()
+            .map(|geoid| {
+                self.geoid_within_geofence(geoid.clone())
+                    .map(|within| (geoid, within))
+            })
+            .collect::<PyResult<Vec<_>>>()
+            .map(|pairs| {
+                pairs
+                    .into_iter()
+                    .filter(|(_, within)| *within)
+                    .map(|(geoid, _)| geoid)
+                    .collect()
+            })
+    }
+
+    fn route_through(
+        &self,
+        origin: EntityPosition,
+        waypoints: Vec<EntityPosition>,
+        destination: EntityPosition,
+        keep_first: bool,
+        keep_last: bool,
+    ) -> PyResult<(Vec<LinkTraversal>, f64)> {
+        let stops: Vec<EntityPosition> = std::iter::once(origin)
+            .chain(waypoints.into_iter())
+            .chain(std::iter::once(destination))
+            .collect();
+
+        let (order, _) = optimal_waypoint_order(stops.len() - 2, keep_first, keep_last, |a, b| {
+            self.distance_by_geoid_km(stops[a].geoid.clone(), stops[b].geoid.clone())
+        })?;
+
+        concatenate_route(&stops, &order, |from, to| self.route(from, to))
+    }
+
+    fn route_polyline(&self, origin: EntityPosition, destination: EntityPosition) -> PyResult<String> {
+        let links = self.route(origin, destination)?;
+        Self::polyline_for_links(links)
+    }
+
+    #[staticmethod]
+    fn polyline_for_links(links: Vec<LinkTraversal>) -> PyResult<String> {
+        let coords = coords_for_links(&links)
+            .map_err(|e| PyValueError::new_err(format!("Failure decoding link geoids: {}", e)))?;
+        Ok(encode_polyline(&coords))
+    }
+}
+
+fn parse_geofence_rings(geojson: &str) -> Result<Vec<Vec<(f64, f64)>>> {
+    let value: serde_json::Value = serde_json::from_str(geojson)?;
+    let geometry_type = value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| anyhow!("Geofence GeoJSON missing a \"type\" field"))?;
+    let coordinates = value
+        .get("coordinates")
+        .ok_or_else(|| anyhow!("Geofence GeoJSON missing a \"coordinates\" field"))?;
+
+    let polygons: Vec<&serde_json::Value> = match geometry_type {
+        "Polygon" => vec![coordinates],
+        "MultiPolygon" => coordinates
+            .as_array()
+            .ok_or_else(|| anyhow!("MultiPolygon coordinates must be an array of polygons"))?
+            .iter()
+            .collect(),
+        other => return Err(anyhow!("Unsupported geofence geometry type: {}", other)),
+    };
+
+    let mut rings = Vec::new();
+    for polygon in polygons {
+        let ring_arrays = polygon
+            .as_array()
+            .ok_or_else(|| anyhow!("Polygon coordinates must be an array of rings"))?;
+        for ring in ring_arrays {
+            let points = ring
+                .as_array()
+                .ok_or_else(|| anyhow!("Ring coordinates must be an array of positions"))?
+                .iter()
+                .map(|position| {
+                    let coords = position
+                        .as_array()
+                        .ok_or_else(|| anyhow!("Position must be an array of [lon, lat]"))?;
+                    let lon = coords
+                        .first()
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| anyhow!("Position missing a longitude"))?;
+                    let lat = coords
+                        .get(1)
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| anyhow!("Position missing a latitude"))?;
+                    Ok((lon, lat))
+                })
+                .collect::<Result<Vec<(f64, f64)>>>()?;
+            rings.push(points);
+        }
+    }
+    Ok(rings)
+}
+
+// Half-open edge rule (`(yi > y) != (yj > y)`) so a ray through a shared
+// vertex is never counted twice.
+fn point_in_rings(point: (f64, f64), rings: &[Vec<(f64, f64)>]) -> bool {
+    let (x, y) = point;
+    let mut inside = false;
+    for ring in rings {
+        let n = ring.len();
+        if n < 3 {
+            continue;
+        }
+        for i in 0..n {
+            let (xi, yi) = ring[i];
+            let (xj, yj) = ring[(i + n - 1) % n];
+            if (yi > y) != (yj > y) {
+                let x_intersect = xi + (y - yi) / (yj - yi) * (xj - xi);
+                if x < x_intersect {
+                    inside = !inside;
+                }
+            }
+        }
+    }
+    inside
+}
+
+struct HeapEntry {
+    cost: f64,
+    node: GeoidString,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    // Reversed so `BinaryHeap` (a max-heap) pops the lowest cost first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[pyclass]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GraphRoadNetwork {
+    #[pyo3(get)]
+    sim_h3_resolution: usize,
+    adjacency: HashMap<GeoidString, Vec<LinkTraversal>>,
+    #[serde(skip)]
+    node_index: OnceCell<RTree<IndexedNode>>,
+    // Keyed by (hub, node): distance_km along the hub's shortest-time path,
+    // and the predecessor link into `node` on that path.
+    #[serde(skip)]
+    travel_time_cache: HashMap<(GeoidString, GeoidString), (f64, LinkId)>,
+}
+
+#[pymethods]
+impl GraphRoadNetwork {
+    #[new]
+    fn new(
+        adjacency: HashMap<GeoidString, Vec<LinkTraversal>>,
+        sim_h3_resolution: Option<usize>,
+    ) -> PyResult<Self> {
+        let res = match sim_h3_resolution {
+            Some(res) => res,
+            None => 15,
+        };
+        Ok(GraphRoadNetwork {
+            sim_h3_resolution: res,
+            adjacency,
+            node_index: OnceCell::new(),
+            travel_time_cache: HashMap::new(),
+        })
+    }
+
+    fn route(
+        &self,
+        origin: EntityPosition,
+        destination: EntityPosition,
+    ) -> PyResult<Vec<LinkTraversal>> {
+        if origin == destination {
+            return Ok(Vec::new());
+        }
+
+        if self
+            .travel_time_cache
+            .contains_key(&(origin.geoid.clone(), destination.geoid.clone()))
+        {
+            return self.reconstruct_from_cache(&origin.geoid, &destination.geoid);
+        }
+
+        self.astar(&origin.geoid, &destination.geoid)
+            .map_err(|e| PyValueError::new_err(format!("Failure computing route: {}", e)))
+    }
+
+    fn distance_by_geoid_km(&self, origin: GeoidString, destination: GeoidString) -> PyResult<f64> {
+        if origin == destination {
+            return Ok(0.0);
+        }
+
+        if let Some((distance_km, _)) = self
+            .travel_time_cache
+            .get(&(origin.clone(), destination.clone()))
+        {
+            return Ok(*distance_km);
+        }
+
+        let links = self
+            .astar(&origin, &destination)
+            .map_err(|e| PyValueError::new_err(format!("Failure computing route: {}", e)))?;
+        Ok(links.iter().map(|link| link.distance_km).sum())
+    }
+
+    fn travel_time_by_geoid_s(&self, origin: GeoidString, destination: GeoidString) -> PyResult<f64> {
+        if origin == destination {
+            return Ok(0.0);
+        }
+
+        let links = if self
+            .travel_time_cache
+            .contains_key(&(origin.clone(), destination.clone()))
+        {
+            self.reconstruct_from_cache(&origin, &destination)?
+        } else {
+            self.astar(&origin, &destination)
+                .map_err(|e| PyValueError::new_err(format!("Failure computing route: {}", e)))?
+        };
+        Ok(links
+            .iter()
+            .map(|link| link.distance_km / link.speed_kmph * 3600.0)
+            .sum())
+    }
+
+    fn precompute_travel_times(&mut self, hubs: Vec<GeoidString>) -> PyResult<()> {
+        for hub in hubs {
+            for (node, entry) in self.dijkstra_from_hub(&hub) {
+                self.travel_time_cache.insert((hub.clone(), node), entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn save_travel_time_cache(&self, path: String) -> PyResult<()> {
+        let bytes = bincode::serialize(&self.travel_time_cache).map_err(|e| {
+            PyValueError::new_err(format!("Failure serializing travel-time cache: {}", e))
+        })?;
+        std::fs::write(&path, bytes).map_err(|e| {
+            PyValueError::new_err(format!(
+                "Failure writing travel-time cache to {}: {}",
+                path, e
+            ))
+        })
+    }
+
+    fn load_travel_time_cache(&mut self, path: String) -> PyResult<()> {
+        let bytes = std::fs::read(&path).map_err(|e| {
+            PyValueError::new_err(format!(
+                "Failure reading travel-time cache from {}: {}",
+                path, e
+            ))
+        })?;
+        self.travel_time_cache = bincode::deserialize(&bytes).map_err(|e| {
+            PyValueError::new_err(format!("Failure deserializing travel-time cache: {}", e))
+        })?;
+        Ok(())
+    }
+
+    fn link_from_link_id(&self, link_id: LinkId) -> PyResult<LinkTraversal> {
+        let (source, _dest) = match link_id_to_geoids(&link_id) {
+            Ok(geoids) => geoids,
+            Err(e) => {
+                return Err(PyValueError::new_err(format!(
+                    "Error converting link id to geoid {}",
+                    e
+                )))
+            }
+        };
+        self.adjacency
+            .get(&source)
+            .and_then(|edges| edges.iter().find(|edge| edge.link_id == link_id))
+            .cloned()
+            .ok_or_else(|| PyValueError::new_err(format!("No edge found for link id {}", link_id)))
+    }
+
+    fn route_through(
+        &self,
+        origin: EntityPosition,
+        waypoints: Vec<EntityPosition>,
+        destination: EntityPosition,
+        keep_first: bool,
+        keep_last: bool,
+    ) -> PyResult<(Vec<LinkTraversal>, f64)> {
+        let stops: Vec<EntityPosition> = std::iter::once(origin)
+            .chain(waypoints.into_iter())
+            .chain(std::iter::once(destination))
+            .collect();
+
+        let (order, _) = optimal_waypoint_order(stops.len() - 2, keep_first, keep_last, |a, b| {
+            self.travel_time_by_geoid_s(stops[a].geoid.clone(), stops[b].geoid.clone())
+        })?;
+
+        concatenate_route(&stops, &order, |from, to| self.route(from, to))
+    }
+
+    fn nearest_node(&self, lat: f64, lon: f64) -> PyResult<GeoidString> {
+        self.node_index()?
+            .nearest_neighbor(&lonlat_to_unit_sphere(lon, lat))
+            .map(|node| node.geoid.clone())
+            .ok_or_else(|| PyValueError::new_err("Road network has no nodes to snap to"))
+    }
+
+    fn position_from_latlon(&self, lat: f64, lon: f64) -> PyResult<EntityPosition> {
+        let geoid = self.nearest_node(lat, lon)?;
+        Ok(EntityPosition {
+            link_id: geoid_string_to_link_id(&geoid, &geoid),
+            geoid,
+        })
+    }
+
+    fn route_polyline(&self, origin: EntityPosition, destination: EntityPosition) -> PyResult<String> {
+        let links = self.route(origin, destination)?;
+        Self::polyline_for_links(links)
+    }
+
+    #[staticmethod]
+    fn polyline_for_links(links: Vec<LinkTraversal>) -> PyResult<String> {
+        let coords = coords_for_links(&links)
+            .map_err(|e| PyValueError::new_err(format!("Failure decoding link geoids: {}", e)))?;
+        Ok(encode_polyline(&coords))
+    }
+}
+
+impl GraphRoadNetwork {
+    fn max_speed_kmph(&self) -> f64 {
+        self.adjacency
+            .values()
+            .flatten()
+            .map(|link| link.speed_kmph)
+            .fold(AVG_SPEED_KMPH, f64::max)
+    }
+
+    fn astar(&self, origin: &GeoidString, destination: &GeoidString) -> Result<Vec<LinkTraversal>> {
+        let max_speed_kmph = self.max_speed_kmph();
+        let heuristic_secs = |node: &GeoidString| -> Result<f64> {
+            Ok(h3_dist_km(node, destination)? / max_speed_kmph * 3600.0)
+        };
+
+        let mut g_score: HashMap<GeoidString, f64> = HashMap::new();
+        let mut came_from: HashMap<GeoidString, LinkTraversal> = HashMap::new();
+        let mut closed: HashSet<GeoidString> = HashSet::new();
+        let mut open = BinaryHeap::new();
+
+        g_score.insert(origin.clone(), 0.0);
+        open.push(HeapEntry {
+            cost: heuristic_secs(origin)?,
+            node: origin.clone(),
+        });
+
+        while let Some(HeapEntry { node, .. }) = open.pop() {
+            if &node == destination {
+                return self.reconstruct_path(origin, destination, &came_from);
+            }
+            if !closed.insert(node.clone()) {
+                continue;
+            }
+
+            let g_here = g_score[&node];
+            for edge in self.adjacency.get(&node).into_iter().flatten() {
+                let tentative_g = g_here + edge.distance_km / edge.speed_kmph * 3600.0;
+                if tentative_g < *g_score.get(&edge.end).unwrap_or(&f64::INFINITY) {
+                    g_score.insert(edge.end.clone(), tentative_g);
+                    came_from.insert(edge.end.clone(), edge.clone());
+                    open.push(HeapEntry {
+                        cost: tentative_g + heuristic_secs(&edge.end)?,
+                        node: edge.end.clone(),
+                    });
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "No path found from {} to {}",
+            origin,
+            destination
+        ))
+    }
+
+    // `came_from` stores the actual traversed edge per node (rather than
+    // re-deriving it from a link_id convention), so this can't panic on a
+    // caller-supplied adjacency with its own link_id scheme and can't pick
+    // the wrong parallel edge between the same pair of nodes.
+    fn reconstruct_path(
+        &self,
+        origin: &GeoidString,
+        destination: &GeoidString,
+        came_from: &HashMap<GeoidString, LinkTraversal>,
+    ) -> Result<Vec<LinkTraversal>> {
+        let mut links = Vec::new();
+        let mut current = destination.clone();
+        while &current != origin {
+            let edge = came_from.get(&current).cloned().ok_or_else(|| {
+                anyhow!(
+                    "No traversed edge recorded for {} while reconstructing route",
+                    current
+                )
+            })?;
+            current = edge.start.clone();
+            links.push(edge);
+        }
+        links.reverse();
+        Ok(links)
+    }
+
+    /// The spatial index over every node geoid in the adjacency map, whether
+    /// a key (including nodes with no edges) or an edge's destination, built
+    /// on first use and reused for every later snap.
+    fn node_index(&self) -> PyResult<&RTree<IndexedNode>> {
+        if let Some(tree) = self.node_index.get() {
+            return Ok(tree);
+        }
+
+        let mut seen: HashSet<&GeoidString> = HashSet::new();
+        let mut nodes = Vec::new();
+        for (origin, edges) in &self.adjacency {
+            for geoid in std::iter::once(origin).chain(edges.iter().map(|edge| &edge.end)) {
+                if seen.insert(geoid) {
+                    let (lon, lat) = geoid_to_lonlat(geoid).map_err(|e| {
+                        PyValueError::new_err(format!(
+                            "Failure decoding node geoid {}: {}",
+                            geoid, e
+                        ))
+                    })?;
+                    nodes.push(IndexedNode {
+                        point: lonlat_to_unit_sphere(lon, lat),
+                        geoid: geoid.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(self.node_index.get_or_init(|| RTree::bulk_load(nodes)))
+    }
+
+    fn dijkstra_from_hub(&self, hub: &GeoidString) -> HashMap<GeoidString, (f64, LinkId)> {
+        let mut time_s: HashMap<GeoidString, f64> = HashMap::new();
+        let mut distance_km: HashMap<GeoidString, f64> = HashMap::new();
+        let mut predecessor: HashMap<GeoidString, LinkId> = HashMap::new();
+        let mut closed: HashSet<GeoidString> = HashSet::new();
+        let mut open = BinaryHeap::new();
+
+        time_s.insert(hub.clone(), 0.0);
+        distance_km.insert(hub.clone(), 0.0);
+        open.push(HeapEntry {
+            cost: 0.0,
+            node: hub.clone(),
+        });
+
+        while let Some(HeapEntry { node, .. }) = open.pop() {
+            if !closed.insert(node.clone()) {
+                continue;
+            }
+            let node_time = time_s[&node];
+            let node_distance_km = distance_km[&node];
+            for edge in self.adjacency.get(&node).into_iter().flatten() {
+                let tentative_time = node_time + edge.distance_km / edge.speed_kmph * 3600.0;
+                if tentative_time < *time_s.get(&edge.end).unwrap_or(&f64::INFINITY) {
+                    time_s.insert(edge.end.clone(), tentative_time);
+                    distance_km.insert(edge.end.clone(), node_distance_km + edge.distance_km);
+                    predecessor.insert(edge.end.clone(), edge.link_id.clone());
+                    open.push(HeapEntry {
+                        cost: tentative_time,
+                        node: edge.end.clone(),
+                    });
+                }
+            }
+        }
+
+        distance_km
+            .into_iter()
+            .filter(|(node, _)| node != hub)
+            .map(|(node, km)| {
+                let link_id = predecessor[&node].clone();
+                (node, (km, link_id))
+            })
+            .collect()
+    }
+
+    fn reconstruct_from_cache(
+        &self,
+        hub: &GeoidString,
+        destination: &GeoidString,
+    ) -> PyResult<Vec<LinkTraversal>> {
+        let mut links = Vec::new();
+        let mut current = destination.clone();
+        while &current != hub {
+            let link_id = self
+                .travel_time_cache
+                .get(&(hub.clone(), current.clone()))
+                .map(|(_, link_id)| link_id.clone())
+                .ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "Travel-time cache missing entry for {} -> {}",
+                        hub, current
+                    ))
+                })?;
+            let edge = self.link_from_link_id(link_id)?;
+            current = edge.start.clone();
+            links.push(edge);
+        }
+        links.reverse();
+        Ok(links)
+    }
+}
+
+/// Projects a `(lon, lat)` point onto the unit sphere as `(x, y, z)`, so
+/// Euclidean ranking between points stays correct across the antimeridian
+/// and near the poles, where it isn't in raw `(lon, lat)` degrees.
+fn lonlat_to_unit_sphere(lon: f64, lat: f64) -> [f64; 3] {
+    let lon = lon.to_radians();
+    let lat = lat.to_radians();
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+#[derive(Clone)]
+struct IndexedNode {
+    point: [f64; 3],
+    geoid: GeoidString,
+}
+
+impl RTreeObject for IndexedNode {
+    type Envelope = AABB<[f64; 3]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.point)
+    }
+}
+
+impl PointDistance for IndexedNode {
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        let dx = self.point[0] - point[0];
+        let dy = self.point[1] - point[1];
+        let dz = self.point[2] - point[2];
+        dx * dx + dy * dy + dz * dz
+    }
+}
+
+/// `speed_column` is optional: when absent, every edge falls back to
+/// `AVG_SPEED_KMPH`. `node_table`/`node_id_column` are optional together:
+/// when given, nodes they name are seeded into the adjacency map with no
+/// edges, so isolated nodes (no incident edge in `edge_table`) still show
+/// up for `nearest_node`/`position_from_latlon` snapping.
+#[pyclass]
+#[derive(Clone)]
+pub struct RoadNetworkSource {
+    #[pyo3(get)]
+    edge_table: String,
+    #[pyo3(get)]
+    geometry_column: String,
+    #[pyo3(get)]
+    origin_node_column: String,
+    #[pyo3(get)]
+    destination_node_column: String,
+    #[pyo3(get)]
+    speed_column: Option<String>,
+    #[pyo3(get)]
+    node_table: Option<String>,
+    #[pyo3(get)]
+    node_id_column: Option<String>,
+}
+
+#[pymethods]
+impl RoadNetworkSource {
+    #[new]
+    fn new(
+        edge_table: String,
+        geometry_column: String,
+        origin_node_column: String,
+        destination_node_column: String,
+        speed_column: Option<String>,
+        node_table: Option<String>,
+        node_id_column: Option<String>,
+    ) -> Self {
+        RoadNetworkSource {
+            edge_table,
+            geometry_column,
+            origin_node_column,
+            destination_node_column,
+            speed_column,
+            node_table,
+            node_id_column,
+        }
+    }
+}
+
+fn polyline_length_km(coords: &[(f64, f64)]) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+    coords
+        .windows(2)
+        .map(|pair| {
+            let (lon1, lat1) = pair[0];
+            let (lon2, lat2) = pair[1];
+            let (lat1, lat2, dlat, dlon) = (
+                lat1.to_radians(),
+                lat2.to_radians(),
+                (lat2 - lat1).to_radians(),
+                (lon2 - lon1).to_radians(),
+            );
+            let a = (dlat / 2.0).sin().powi(2)
+                + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+            EARTH_RADIUS_KM * 2.0 * a.sqrt().asin()
+        })
+        .sum()
+}
+
+fn parse_wkb_linestring(wkb: &[u8]) -> Result<Vec<(f64, f64)>> {
+    if wkb.len() < 9 {
+        return Err(anyhow!("WKB geometry too short to contain a header"));
+    }
+    let little_endian = wkb[0] == 1;
+    let read_u32 = |bytes: &[u8]| -> u32 {
+        if little_endian {
+            u32::from_le_bytes(bytes.try_into().unwrap())
+        } else {
+            u32::from_be_bytes(bytes.try_into().unwrap())
+        }
+    };
+    let read_f64 = |bytes: &[u8]| -> f64 {
+        if little_endian {
+            f64::from_le_bytes(bytes.try_into().unwrap())
+        } else {
+            f64::from_be_bytes(bytes.try_into().unwrap())
+        }
+    };
+
+    let geometry_type = read_u32(&wkb[1..5]) & 0xff;
+    if geometry_type != 2 {
+        return Err(anyhow!(
+            "Expected a WKB LineString (type 2), found type {}",
+            geometry_type
+        ));
+    }
+
+    let num_points = read_u32(&wkb[5..9]) as usize;
+    let mut coords = Vec::with_capacity(num_points);
+    let mut offset = 9;
+    for _ in 0..num_points {
+        if wkb.len() < offset + 16 {
+            return Err(anyhow!("WKB LineString truncated before expected point"));
+        }
+        let lon = read_f64(&wkb[offset..offset + 8]);
+        let lat = read_f64(&wkb[offset + 8..offset + 16]);
+        coords.push((lon, lat));
+        offset += 16;
+    }
+    Ok(coords)
+}
+
+// GeoPackage Binary Header: 2-byte magic, version, flags, 4-byte SRS id,
+// then an envelope sized by the flags' envelope-indicator bits.
+fn gpkg_blob_to_wkb(blob: &[u8]) -> Result<&[u8]> {
+    if blob.len() < 8 || &blob[0..2] != b"GP" {
+        return Err(anyhow!("Not a GeoPackage geometry blob"));
+    }
+    let flags = blob[3];
+    let envelope_words = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 4,
+        2 | 3 => 6,
+        4 => 8,
+        _ => return Err(anyhow!("Unrecognized GeoPackage envelope indicator")),
+    };
+    let header_len = 8 + envelope_words * 8;
+    if blob.len() < header_len {
+        return Err(anyhow!("GeoPackage geometry blob shorter than its header"));
+    }
+    Ok(&blob[header_len..])
+}
+
+/// Double-quotes a SQL identifier, doubling any embedded quote, so
+/// `RoadNetworkSource`'s caller-supplied table/column names can't break out
+/// of the identifier position when spliced into a query string.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+impl GraphRoadNetwork {
+    pub fn load_geopackage(path: &str, cfg: &RoadNetworkSource) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path)?;
+        let speed_select = match &cfg.speed_column {
+            Some(col) => format!(", {}", quote_ident(col)),
+            None => String::new(),
+        };
+        let query = format!(
+            "SELECT {origin}, {dest}, {geom}{speed_select} FROM {table}",
+            origin = quote_ident(&cfg.origin_node_column),
+            dest = quote_ident(&cfg.destination_node_column),
+            geom = quote_ident(&cfg.geometry_column),
+            speed_select = speed_select,
+            table = quote_ident(&cfg.edge_table),
+        );
+
+        let mut adjacency: HashMap<GeoidString, Vec<LinkTraversal>> = HashMap::new();
+        let mut stmt = conn.prepare(&query)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let origin: GeoidString = row.get(0)?;
+            let destination: GeoidString = row.get(1)?;
+            let geom_blob: Vec<u8> = row.get(2)?;
+            let coords = parse_wkb_linestring(gpkg_blob_to_wkb(&geom_blob)?)?;
+            let speed_kmph = match &cfg.speed_column {
+                Some(_) => row.get::<_, f64>(3)?,
+                None => AVG_SPEED_KMPH,
+            };
+
+            adjacency
+                .entry(origin.clone())
+                .or_default()
+                .push(LinkTraversal {
+                    link_id: geoid_string_to_link_id(&origin, &destination),
+                    start: origin,
+                    end: destination,
+                    distance_km: polyline_length_km(&coords),
+                    speed_kmph,
+                });
+        }
+
+        if let (Some(node_table), Some(node_id_column)) = (&cfg.node_table, &cfg.node_id_column) {
+            let query = format!(
+                "SELECT {id} FROM {table}",
+                id = quote_ident(node_id_column),
+                table = quote_ident(node_table),
+            );
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let node: GeoidString = row.get(0)?;
+                adjacency.entry(node).or_default();
+            }
+        }
+
+        Ok(GraphRoadNetwork {
+            sim_h3_resolution: 15,
+            adjacency,
+            node_index: OnceCell::new(),
+            travel_time_cache: HashMap::new(),
+        })
+    }
+
+    pub fn load_postgis(url: &str, cfg: &RoadNetworkSource) -> Result<Self> {
+        let mut client = postgres::Client::connect(url, postgres::NoTls)?;
+        let speed_select = match &cfg.speed_column {
+            Some(col) => format!(", {}", quote_ident(col)),
+            None => String::new(),
+        };
+        let query = format!(
+            "SELECT {origin}, {dest}, \
+             ST_Length({geom}::geography) / 1000.0 AS distance_km{speed_select} \
+             FROM {table}",
+            origin = quote_ident(&cfg.origin_node_column),
+            dest = quote_ident(&cfg.destination_node_column),
+            geom = quote_ident(&cfg.geometry_column),
+            speed_select = speed_select,
+            table = quote_ident(&cfg.edge_table),
+        );
+
+        let mut adjacency: HashMap<GeoidString, Vec<LinkTraversal>> = HashMap::new();
+        for row in client.query(query.as_str(), &[])? {
+            let origin: GeoidString = row.get(0);
+            let destination: GeoidString = row.get(1);
+            let distance_km: f64 = row.get(2);
+            let speed_kmph = match &cfg.speed_column {
+                Some(_) => row.get::<_, f64>(3),
+                None => AVG_SPEED_KMPH,
+            };
+
+            adjacency
+                .entry(origin.clone())
+                .or_default()
+                .push(LinkTraversal {
+                    link_id: geoid_string_to_link_id(&origin, &destination),
+                    start: origin,
+                    end: destination,
+                    distance_km,
+                    speed_kmph,
+                });
+        }
+
+        if let (Some(node_table), Some(node_id_column)) = (&cfg.node_table, &cfg.node_id_column) {
+            let query = format!(
+                "SELECT {id} FROM {table}",
+                id = quote_ident(node_id_column),
+                table = quote_ident(node_table),
+            );
+            for row in client.query(query.as_str(), &[])? {
+                let node: GeoidString = row.get(0);
+                adjacency.entry(node).or_default();
+            }
+        }
+
+        Ok(GraphRoadNetwork {
+            sim_h3_resolution: 15,
+            adjacency,
+            node_index: OnceCell::new(),
+            travel_time_cache: HashMap::new(),
+        })
+    }
+}
+
+#[pymethods]
+impl GraphRoadNetwork {
+    #[staticmethod]
+    fn from_geopackage(path: String, cfg: RoadNetworkSource) -> PyResult<Self> {
+        GraphRoadNetwork::load_geopackage(&path, &cfg)
+            .map_err(|e| PyValueError::new_err(format!("Failure loading GeoPackage network: {}", e)))
+    }
+
+    #[staticmethod]
+    fn from_postgis(url: String, cfg: RoadNetworkSource) -> PyResult<Self> {
+        GraphRoadNetwork::load_postgis(&url, &cfg)
+            .map_err(|e| PyValueError::new_err(format!("Failure loading PostGIS network: {}", e)))
+    }
+}
+
+fn permutations(items: &[usize]) -> Vec<Vec<usize>> {
+    if items.is_empty() {
+        return vec![Vec::new()];
+    }
+    let mut result = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let item = rest.remove(i);
+        for mut tail in permutations(&rest) {
+            tail.insert(0, item);
+            result.push(tail);
+        }
+    }
+    result
+}
+
+fn permutation_order(
+    free: &[usize],
+    prev: usize,
+    next: usize,
+    distance: &impl Fn(usize, usize) -> PyResult<f64>,
+) -> PyResult<(Vec<usize>, f64)> {
+    let mut best: Option<(Vec<usize>, f64)> = None;
+    for candidate in permutations(free) {
+        let mut path = Vec::with_capacity(candidate.len() + 2);
+        path.push(prev);
+        path.extend(&candidate);
+        path.push(next);
+
+        let mut cost = 0.0;
+        for pair in path.windows(2) {
+            cost += distance(pair[0], pair[1])?;
+        }
+        if best.as_ref().map_or(true, |(_, best_cost)| cost < *best_cost) {
+            best = Some((candidate, cost));
+        }
+    }
+    match best {
+        Some(result) => Ok(result),
+        None => Ok((Vec::new(), distance(prev, next)?)),
+    }
+}
+
+// Held-Karp bitmask DP: best[mask][j] is the min cost of a path from `prev`
+// visiting exactly the free stops in `mask`, ending at free stop `j`.
+fn held_karp_order(
+    free: &[usize],
+    prev: usize,
+    next: usize,
+    distance: &impl Fn(usize, usize) -> PyResult<f64>,
+) -> PyResult<(Vec<usize>, f64)> {
+    let n = free.len();
+    if n == 0 {
+        return Ok((Vec::new(), distance(prev, next)?));
+    }
+
+    let stop_id = |i: usize| -> usize {
+        if i == 0 {
+            prev
+        } else if i == n + 1 {
+            next
+        } else {
+            free[i - 1]
+        }
+    };
+
+    let mut d = vec![vec![0.0; n + 2]; n + 2];
+    for a in 0..=n + 1 {
+        for b in 0..=n + 1 {
+            if a != b {
+                d[a][b] = distance(stop_id(a), stop_id(b))?;
+            }
+        }
+    }
+
+    let full_mask = (1usize << n) - 1;
+    let mut best = vec![vec![f64::INFINITY; n]; 1 << n];
+    let mut parent = vec![vec![usize::MAX; n]; 1 << n];
+
+    for j in 0..n {
+        best[1 << j][j] = d[0][j + 1];
+    }
+
+    for mask in 1..=full_mask {
+        for j in 0..n {
+            if mask & (1 << j) == 0 || best[mask][j].is_infinite() {
+                continue;
+            }
+            let cur = best[mask][j];
+            for k in 0..n {
+                if mask & (1 << k) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << k);
+                let candidate = cur + d[j + 1][k + 1];
+                if candidate < best[next_mask][k] {
+                    best[next_mask][k] = candidate;
+                    parent[next_mask][k] = j;
+                }
+            }
+        }
+    }
+
+    let mut best_end = 0;
+    let mut best_cost = f64::INFINITY;
+    for j in 0..n {
+        let total = best[full_mask][j] + d[j + 1][n + 1];
+        if total < best_cost {
+            best_cost = total;
+            best_end = j;
+        }
+    }
+
+    let mut order = Vec::with_capacity(n);
+    let mut mask = full_mask;
+    let mut j = best_end;
+    loop {
+        order.push(free[j]);
+        let prev_j = parent[mask][j];
+        if prev_j == usize::MAX {
+            break;
+        }
+        mask &= !(1 << j);
+        j = prev_j;
+    }
+    order.reverse();
+
+    Ok((order, best_cost))
+}
+
+const PERMUTATION_WAYPOINT_LIMIT: usize = 8;
+
+fn optimal_waypoint_order(
+    n_waypoints: usize,
+    keep_first: bool,
+    keep_last: bool,
+    distance: impl Fn(usize, usize) -> PyResult<f64>,
+) -> PyResult<(Vec<usize>, f64)> {
+    let origin = 0;
+    let destination = n_waypoints + 1;
+
+    let first_frozen = if keep_first && n_waypoints > 0 {
+        Some(1)
+    } else {
+        None
+    };
+    let last_frozen = if keep_last && n_waypoints > 0 {
+        Some(n_waypoints)
+    } else {
+        None
+    };
+
+    let free: Vec<usize> = (1..=n_waypoints)
+        .filter(|i| Some(*i) != first_frozen && Some(*i) != last_frozen)
+        .collect();
+
+    let prev = first_frozen.unwrap_or(origin);
+    let next = last_frozen.unwrap_or(destination);
+
+    let (free_order, _) = if free.len() <= PERMUTATION_WAYPOINT_LIMIT {
+        permutation_order(&free, prev, next, &distance)?
+    } else {
+        held_karp_order(&free, prev, next, &distance)?
+    };
+
+    let mut order = Vec::with_capacity(n_waypoints + 2);
+    order.push(origin);
+    order.extend(first_frozen);
+    order.extend(&free_order);
+    order.extend(last_frozen);
+    order.push(destination);
+
+    let mut total = 0.0;
+    for pair in order.windows(2) {
+        total += distance(pair[0], pair[1])?;
+    }
+
+    Ok((order, total))
+}
+
+fn concatenate_route(
+    stops: &[EntityPosition],
+    order: &[usize],
+    route_leg: impl Fn(EntityPosition, EntityPosition) -> PyResult<Vec<LinkTraversal>>,
+) -> PyResult<(Vec<LinkTraversal>, f64)> {
+    let mut links = Vec::new();
+    let mut total_km = 0.0;
+    for pair in order.windows(2) {
+        let leg = route_leg(stops[pair[0]].clone(), stops[pair[1]].clone())?;
+        total_km += leg.iter().map(|link| link.distance_km).sum::<f64>();
+        links.extend(leg);
+    }
+    Ok((links, total_km))
+}
+
+fn coords_for_links(links: &[LinkTraversal]) -> Result<Vec<(f64, f64)>> {
+    let mut coords = Vec::with_capacity(links.len() + 1);
+    for (i, link) in links.iter().enumerate() {
+        if i == 0 {
+            let (lon, lat) = geoid_to_lonlat(&link.start)?;
+            coords.push((lat, lon));
+        }
+        let (lon, lat) = geoid_to_lonlat(&link.end)?;
+        coords.push((lat, lon));
+    }
+    Ok(coords)
+}
+
+fn encode_polyline_value(value: i64) -> String {
+    let mut zigzag = value << 1;
+    if value < 0 {
+        zigzag = !zigzag;
+    }
+
+    let mut encoded = String::new();
+    while zigzag >= 0x20 {
+        encoded.push((((zigzag & 0x1f) | 0x20) as u8 + 63) as char);
+        zigzag >>= 5;
+    }
+    encoded.push((zigzag as u8 + 63) as char);
+    encoded
+}
+
+fn encode_polyline(coords: &[(f64, f64)]) -> String {
+    let mut encoded = String::new();
+    let mut prev_lat = 0i64;
+    let mut prev_lon = 0i64;
+    for &(lat, lon) in coords {
+        let lat_e5 = (lat * 1e5).round() as i64;
+        let lon_e5 = (lon * 1e5).round() as i64;
+        encoded.push_str(&encode_polyline_value(lat_e5 - prev_lat));
+        encoded.push_str(&encode_polyline_value(lon_e5 - prev_lon));
+        prev_lat = lat_e5;
+        prev_lon = lon_e5;
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_entry_orders_lowest_cost_first() {
+        let mut heap = BinaryHeap::new();
+        heap.push(HeapEntry {
+            cost: 5.0,
+            node: "b".to_string(),
+        });
+        heap.push(HeapEntry {
+            cost: 1.0,
+            node: "a".to_string(),
+        });
+        heap.push(HeapEntry {
+            cost: 3.0,
+            node: "c".to_string(),
+        });
+
+        assert_eq!(heap.pop().unwrap().node, "a");
+        assert_eq!(heap.pop().unwrap().node, "c");
+        assert_eq!(heap.pop().unwrap().node, "b");
+    }
+
+    #[test]
+    fn gpkg_blob_strips_header_and_decodes_linestring() {
+        let mut blob = vec![b'G', b'P', 0, 0b0000_0000, 0, 0, 0, 0];
+        blob.extend(wkb_linestring(&[(1.0, 2.0), (3.0, 4.0)]));
+
+        let wkb = gpkg_blob_to_wkb(&blob).unwrap();
+        let coords = parse_wkb_linestring(wkb).unwrap();
+        assert_eq!(coords, vec![(1.0, 2.0), (3.0, 4.0)]);
+    }
+
+    fn wkb_linestring(coords: &[(f64, f64)]) -> Vec<u8> {
+        let mut wkb = vec![1u8];
+        wkb.extend((2u32).to_le_bytes());
+        wkb.extend((coords.len() as u32).to_le_bytes());
+        for (lon, lat) in coords {
+            wkb.extend(lon.to_le_bytes());
+            wkb.extend(lat.to_le_bytes());
+        }
+        wkb
+    }
+
+    #[test]
+    fn held_karp_matches_brute_force_permutation_search() {
+        // 0 = origin, 1..=4 = waypoints, 5 = destination.
+        let d: [[f64; 6]; 6] = [
+            [0.0, 2.0, 9.0, 10.0, 7.0, 3.0],
+            [2.0, 0.0, 6.0, 4.0, 3.0, 8.0],
+            [9.0, 6.0, 0.0, 8.0, 5.0, 4.0],
+            [10.0, 4.0, 8.0, 0.0, 6.0, 2.0],
+            [7.0, 3.0, 5.0, 6.0, 0.0, 9.0],
+            [3.0, 8.0, 4.0, 2.0, 9.0, 0.0],
+        ];
+        let distance = |a: usize, b: usize| -> PyResult<f64> { Ok(d[a][b]) };
+        let free = [1, 2, 3, 4];
+
+        let (permutation_order_result, permutation_cost) =
+            permutation_order(&free, 0, 5, &distance).unwrap();
+        let (held_karp_order_result, held_karp_cost) =
+            held_karp_order(&free, 0, 5, &distance).unwrap();
+
+        assert_eq!(permutation_cost, held_karp_cost);
+        assert_eq!(permutation_order_result, held_karp_order_result);
+    }
+
+    #[test]
+    fn unit_sphere_projection_keeps_antimeridian_neighbors_close() {
+        let near_dateline_east = lonlat_to_unit_sphere(179.9, 10.0);
+        let near_dateline_west = lonlat_to_unit_sphere(-179.9, 10.0);
+        let across_the_globe = lonlat_to_unit_sphere(0.0, 10.0);
+
+        let dist = |a: [f64; 3], b: [f64; 3]| -> f64 {
+            (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f64>()
+        };
+
+        assert!(
+            dist(near_dateline_east, near_dateline_west) < dist(near_dateline_east, across_the_globe)
+        );
+    }
+
+    #[test]
+    fn point_in_rings_tests_a_known_square() {
+        let square = vec![vec![(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)]];
+
+        assert!(point_in_rings((5.0, 5.0), &square));
+        assert!(!point_in_rings((15.0, 5.0), &square));
+        assert!(!point_in_rings((5.0, -1.0), &square));
+    }
+
+    #[test]
+    fn encode_polyline_matches_reference_example() {
+        // Google's encoded polyline algorithm reference example.
+        let coords = vec![(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)];
+        assert_eq!(encode_polyline(&coords), "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+    }
+
+    #[test]
+    fn dijkstra_from_hub_prefers_the_faster_multi_hop_path() {
+        let mut adjacency: HashMap<GeoidString, Vec<LinkTraversal>> = HashMap::new();
+        adjacency.insert(
+            "a".to_string(),
+            vec![
+                LinkTraversal {
+                    link_id: "a-b".to_string(),
+                    start: "a".to_string(),
+                    end: "b".to_string(),
+                    distance_km: 10.0,
+                    speed_kmph: 10.0, // 1 hour direct.
+                },
+                LinkTraversal {
+                    link_id: "a-c".to_string(),
+                    start: "a".to_string(),
+                    end: "c".to_string(),
+                    distance_km: 5.0,
+                    speed_kmph: 50.0, // 6 minutes.
+                },
+            ],
+        );
+        adjacency.insert(
+            "c".to_string(),
+            vec![LinkTraversal {
+                link_id: "c-b".to_string(),
+                start: "c".to_string(),
+                end: "b".to_string(),
+                distance_km: 5.0,
+                speed_kmph: 50.0, // another 6 minutes, beating the direct hour.
+            }],
+        );
+
+        let network = GraphRoadNetwork::new(adjacency, None).unwrap();
+        let results = network.dijkstra_from_hub(&"a".to_string());
+
+        let (distance_km, link_id) = &results["b"];
+        assert_eq!(*distance_km, 10.0);
+        assert_eq!(link_id, "c-b");
+    }
+
+    #[test]
+    fn route_reconstructs_the_faster_multi_hop_path_end_to_end() {
+        let mut adjacency: HashMap<GeoidString, Vec<LinkTraversal>> = HashMap::new();
+        adjacency.insert(
+            "a".to_string(),
+            vec![
+                LinkTraversal {
+                    link_id: "a-b".to_string(),
+                    start: "a".to_string(),
+                    end: "b".to_string(),
+                    distance_km: 10.0,
+                    speed_kmph: 10.0, // 1 hour direct.
+                },
+                LinkTraversal {
+                    link_id: "a-c".to_string(),
+                    start: "a".to_string(),
+                    end: "c".to_string(),
+                    distance_km: 5.0,
+                    speed_kmph: 50.0, // 6 minutes.
+                },
+            ],
+        );
+        adjacency.insert(
+            "c".to_string(),
+            vec![LinkTraversal {
+                link_id: "c-b".to_string(),
+                start: "c".to_string(),
+                end: "b".to_string(),
+                distance_km: 5.0,
+                speed_kmph: 50.0, // another 6 minutes, beating the direct hour.
+            }],
+        );
+
+        let network = GraphRoadNetwork::new(adjacency, None).unwrap();
+        let origin = EntityPosition {
+            link_id: geoid_string_to_link_id(&"a".to_string(), &"a".to_string()),
+            geoid: "a".to_string(),
+        };
+        let destination = EntityPosition {
+            link_id: geoid_string_to_link_id(&"b".to_string(), &"b".to_string()),
+            geoid: "b".to_string(),
+        };
+
+        let links = network.route(origin, destination).unwrap();
+
+        assert_eq!(
+            links.iter().map(|link| link.link_id.clone()).collect::<Vec<_>>(),
+            vec!["a-c".to_string(), "c-b".to_string()]
+        );
+    }
+
+    #[test]
+    fn route_through_orders_waypoints_by_travel_time_not_distance() {
+        // a-w1 and w2-b are short but slow; a-w2 and w1-b are long but fast.
+        // w1-w2 is short and slow both ways, so every leg's fastest path is its
+        // direct edge. Visiting w1 then w2 is shorter in km (3 vs 19); visiting
+        // w2 then w1 is faster.
+        let link = |link_id: &str, start: &str, end: &str, distance_km: f64, speed_kmph: f64| {
+            LinkTraversal {
+                link_id: link_id.to_string(),
+                start: start.to_string(),
+                end: end.to_string(),
+                distance_km,
+                speed_kmph,
+            }
+        };
+
+        let mut adjacency: HashMap<GeoidString, Vec<LinkTraversal>> = HashMap::new();
+        adjacency.insert("a".to_string(), vec![
+            link("a-w1", "a", "w1", 1.0, 1.0),
+            link("a-w2", "a", "w2", 9.0, 900.0),
+        ]);
+        adjacency.insert("w1".to_string(), vec![
+            link("w1-w2", "w1", "w2", 1.0, 1.0),
+            link("w1-b", "w1", "b", 9.0, 900.0),
+        ]);
+        adjacency.insert("w2".to_string(), vec![
+            link("w2-w1", "w2", "w1", 1.0, 1.0),
+            link("w2-b", "w2", "b", 1.0, 1.0),
+        ]);
+
+        let network = GraphRoadNetwork::new(adjacency, None).unwrap();
+        let position = |geoid: &str| EntityPosition {
+            link_id: geoid_string_to_link_id(&geoid.to_string(), &geoid.to_string()),
+            geoid: geoid.to_string(),
+        };
+
+        let (links, _) = network
+            .route_through(
+                position("a"),
+                vec![position("w1"), position("w2")],
+                position("b"),
+                false,
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            links.iter().map(|link| link.link_id.clone()).collect::<Vec<_>>(),
+            vec!["a-w2".to_string(), "w2-w1".to_string(), "w1-b".to_string()]
+        );
     }
 }